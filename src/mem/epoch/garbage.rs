@@ -0,0 +1,205 @@
+// Garbage is data (or arbitrary deferred work) that's been retired from a
+// lock-free structure but may still be visible to other pinned threads.
+// Each piece of garbage is held until every participant has observed two
+// global-epoch advances since it was retired, at which point it's safe to
+// finalize.
+
+use std::mem;
+
+/// Anything that can be finalized once it's safe to reclaim it: the
+/// deferred-execution analogue of a destructor, run out of a boxed trait
+/// object rather than inline.
+pub trait Collectible {
+    fn finalize(self: Box<Self>);
+}
+
+impl<F: FnOnce()> Collectible for F {
+    fn finalize(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+/// `+ Send` so the global bags (shared and drained across threads — see
+/// `global::GarbageBag`) can be `Sync`; see `Local::defer`.
+pub type Bag = Vec<Box<Collectible + Send>>;
+
+/// Thread-local garbage, bucketed by how many epoch advances have elapsed
+/// since it was retired: `new` was retired most recently, `old` has
+/// survived two advances and is now safe to finalize.
+pub struct Local {
+    pub old: Bag,
+    pub cur: Bag,
+    pub new: Bag,
+
+    /// Finalizers left over from an `old` bag whose drain panicked partway
+    /// through. Kept separate from `old`/`cur`/`new` so a retry can never
+    /// be conflated with garbage that's only survived one epoch advance —
+    /// see `collect`. At least as overdue as `old`, so `migrate_garbage`
+    /// hands it off alongside `old`.
+    pub retry: Bag,
+}
+
+impl Local {
+    pub fn new() -> Local {
+        Local { old: Vec::new(), cur: Vec::new(), new: Vec::new(), retry: Vec::new() }
+    }
+
+    /// Defer an arbitrary finalizer until it's safe to run.
+    ///
+    /// A deferred closure isn't necessarily run by the thread that deferred
+    /// it: once `Participant::migrate_garbage` hands it off to the global
+    /// bags, whichever participant's pin next wins the race to advance the
+    /// global epoch (see `Participant::advance_epoch`) drains the global bag
+    /// that becomes due. `F: Send` rules out the unsound case
+    /// of a non-`Send` capture (an `Rc`, a `Cell` shared with this thread)
+    /// running its destructor on another thread.
+    pub fn defer<F: FnOnce() + Send + 'static>(&mut self, f: F) {
+        self.new.push(Box::new(f));
+    }
+
+    /// Schedule a raw pointer to be freed once it's safe to do so.
+    pub unsafe fn reclaim<T: 'static>(&mut self, data: *mut T) {
+        let data = data as usize;
+        self.defer(move || drop(Box::from_raw(data as *mut T)));
+    }
+
+    /// Age the garbage bags by one epoch advance, finalizing (in FIFO
+    /// order) the `old` bag, whose contents had already survived two
+    /// advances before this call.
+    ///
+    /// A finalizer is arbitrary user code and may panic. If it does, the
+    /// `ExitGuard` below runs during unwinding and puts whatever hasn't
+    /// finalized yet back into `self.retry` — *not* `self.old`, which by
+    /// then already holds the just-promoted `cur` bag (garbage that's only
+    /// survived one advance, not two). Splicing the two together would let
+    /// the next `collect()` finalize the promoted-`cur` portion a full
+    /// epoch early, while threads still pinned at the epoch it was retired
+    /// in could be holding pointers into it — a use-after-free. Keeping
+    /// `retry` separate, and always draining it first (since it's more
+    /// overdue than anything else), avoids that. Only once a finalizer's
+    /// own unwind is handled does the panic continue to propagate.
+    pub fn collect(&mut self) {
+        if !self.retry.is_empty() {
+            let mut retry = mem::replace(&mut self.retry, Vec::new());
+            retry.reverse();
+            let guard = ExitGuard { remaining: &mut retry, restore: &mut self.retry };
+            while let Some(g) = guard.remaining.pop() {
+                g.finalize();
+            }
+        }
+
+        let new = mem::replace(&mut self.new, Vec::new());
+        let cur = mem::replace(&mut self.cur, new);
+        let mut old = mem::replace(&mut self.old, cur);
+        old.reverse();
+
+        let guard = ExitGuard { remaining: &mut old, restore: &mut self.retry };
+        while let Some(g) = guard.remaining.pop() {
+            g.finalize();
+        }
+    }
+
+    /// How many pieces of garbage (across all four bags) are pending.
+    pub fn size(&self) -> usize {
+        self.old.len() + self.cur.len() + self.new.len() + self.retry.len()
+    }
+}
+
+/// Guards a garbage bag being drained in-place: if dropped while a
+/// finalizer is unwinding, whatever's left in `remaining` is spliced back
+/// into `restore` instead of being silently dropped (leaked) or left for
+/// a later pass to double-finalize.
+struct ExitGuard<'a> {
+    remaining: &'a mut Bag,
+    restore: &'a mut Bag,
+}
+
+impl<'a> Drop for ExitGuard<'a> {
+    fn drop(&mut self) {
+        if !self.remaining.is_empty() {
+            // `remaining` is still back-to-front (we pop from the back to
+            // finalize in FIFO order); flip it back, then put it ahead of
+            // whatever was already waiting in `restore` since it's the
+            // most overdue.
+            self.remaining.reverse();
+            let mut fixed = mem::replace(&mut *self.remaining, Vec::new());
+            fixed.append(&mut *self.restore);
+            *self.restore = fixed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn panicking_finalizer_is_retried_without_double_finalizing() {
+        let mut local = Local::new();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let r1 = ran.clone();
+        local.defer(move || { r1.fetch_add(1, Ordering::SeqCst); });
+        local.defer(|| panic!("finalizer blew up"));
+        let r3 = ran.clone();
+        local.defer(move || { r3.fetch_add(1, Ordering::SeqCst); });
+
+        // Age all three into `old` so the next `collect()` actually
+        // finalizes them instead of just shuffling bags.
+        local.collect();
+        local.collect();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| local.collect()));
+        assert!(result.is_err(), "the panicking finalizer should still unwind");
+
+        // The first finalizer ran before the panic; the third is still
+        // pending, not leaked, and the participant remains usable.
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert_eq!(local.size(), 1);
+
+        // A later collect() finalizes the retried closure exactly once —
+        // no leak, no double-finalize, and it isn't mistaken for garbage
+        // that's only survived a single epoch advance.
+        local.collect();
+        assert_eq!(ran.load(Ordering::SeqCst), 2);
+        assert_eq!(local.size(), 0);
+    }
+
+    #[test]
+    fn defer_runs_every_finalizer_in_fifo_order_two_advances_later() {
+        let mut local = Local::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for i in 0..3 {
+            let order = order.clone();
+            local.defer(move || order.lock().unwrap().push(i));
+        }
+        assert_eq!(local.size(), 3);
+
+        // Not yet due: two advances have to elapse first.
+        local.collect();
+        assert!(order.lock().unwrap().is_empty());
+        local.collect();
+        assert!(order.lock().unwrap().is_empty());
+
+        local.collect();
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(local.size(), 0);
+    }
+
+    #[test]
+    fn reclaim_frees_the_pointer_via_the_same_bags() {
+        let mut local = Local::new();
+        let data = Box::into_raw(Box::new(42usize));
+        unsafe { local.reclaim(data); }
+        assert_eq!(local.size(), 1);
+
+        local.collect();
+        local.collect();
+        local.collect();
+        assert_eq!(local.size(), 0);
+    }
+}
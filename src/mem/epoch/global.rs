@@ -0,0 +1,89 @@
+// The process-wide epoch counter, garbage bags, and participant registry
+// shared by every thread that ever pins.
+
+use std::mem;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+use mem::epoch::garbage::Bag;
+use mem::epoch::participant::CADENCE;
+use mem::epoch::participants::Participants;
+
+/// One of the three global garbage bags, indexed by epoch mod 3. Unlike a
+/// participant's thread-local bags, this one is reachable from whichever
+/// thread's pin next advances the epoch (see `Participant::advance_epoch`),
+/// so it needs real synchronization rather than just an `UnsafeCell`.
+pub struct GarbageBag {
+    bag: Mutex<Bag>,
+}
+
+impl GarbageBag {
+    fn new() -> GarbageBag {
+        GarbageBag { bag: Mutex::new(Vec::new()) }
+    }
+
+    /// Hand off a participant's local garbage into this bag.
+    pub fn insert(&self, mut new: Bag) {
+        self.bag.lock().unwrap().append(&mut new);
+    }
+
+    /// Finalize everything currently waiting in the bag.
+    pub fn collect(&self) {
+        let due = mem::replace(&mut *self.bag.lock().unwrap(), Vec::new());
+        for g in due {
+            g.finalize();
+        }
+    }
+}
+
+/// The process-wide epoch and garbage state shared by every participant.
+pub struct Collector {
+    pub epoch: AtomicUsize,
+    pub participants: Participants,
+    pub garbage: [GarbageBag; 3],
+
+    /// The cadence between attempts to advance the epoch; see
+    /// `Participant::try_collect`. Lives on the global handle, rather than
+    /// per-participant, since it's a process-wide throughput/latency
+    /// tradeoff, not a per-thread one.
+    cadence: AtomicUsize,
+}
+
+impl Collector {
+    fn new() -> Collector {
+        Collector {
+            epoch: AtomicUsize::new(0),
+            participants: Participants::new(),
+            garbage: [GarbageBag::new(), GarbageBag::new(), GarbageBag::new()],
+            cadence: AtomicUsize::new(CADENCE),
+        }
+    }
+
+    /// Read the current cadence between global-epoch advancement attempts.
+    pub fn cadence(&self) -> usize {
+        self.cadence.load(Relaxed)
+    }
+
+    /// Set the cadence between global-epoch advancement attempts. A
+    /// smaller value advances the epoch more eagerly, trading throughput
+    /// for lower reclamation latency; pass `1` to attempt an advance on
+    /// every pin.
+    pub fn set_cadence(&self, cadence: usize) {
+        self.cadence.store(cadence.max(1), Relaxed);
+    }
+}
+
+static INIT: Once = Once::new();
+static mut COLLECTOR: *const Collector = 0 as *const Collector;
+
+/// The single, process-wide collector instance, lazily initialized on
+/// first use.
+pub fn get() -> &'static Collector {
+    unsafe {
+        INIT.call_once(|| {
+            COLLECTOR = Box::into_raw(Box::new(Collector::new()));
+        });
+        &*COLLECTOR
+    }
+}
@@ -0,0 +1,124 @@
+// Epoch-based reclamation: defer freeing/finalizing anything a concurrent
+// reader might still be looking at until every participant has observed
+// two global-epoch advances since it was retired.
+//
+// - `participant`: per-thread pin/critical-section bookkeeping and local
+//   garbage.
+// - `participants`: the intrusive list of every thread that's ever pinned.
+// - `garbage`: the bag types garbage is bucketed into while it ages.
+// - `global`: the process-wide epoch counter and garbage bags those bags
+//   eventually get handed off to.
+
+pub mod garbage;
+pub mod global;
+pub mod participant;
+pub mod participants;
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mem::epoch::participant::Participant;
+
+/// An atomic pointer to a `T`, used for the intrusive participant list.
+///
+/// Most access goes through `load`, which is protected by a `Guard` and
+/// safe to dereference for as long as the guard lives. The `_raw` methods
+/// bypass that protection entirely; they're only safe to use where the
+/// pointee's lifetime is guaranteed some other way (see
+/// `Participants::enroll`, which only ever publishes a node it just
+/// allocated itself and that's never freed).
+pub struct Atomic<T> {
+    ptr: AtomicUsize,
+    _marker: PhantomData<*mut T>,
+}
+
+unsafe impl<T> Sync for Atomic<T> {}
+
+impl<T> Atomic<T> {
+    pub fn null() -> Atomic<T> {
+        Atomic { ptr: AtomicUsize::new(0), _marker: PhantomData }
+    }
+
+    /// Load the pointer, returning a reference scoped to the guard's
+    /// lifetime if it's non-null.
+    pub fn load<'a>(&self, _guard: &'a Guard) -> Option<&'a T> {
+        let ptr = self.load_raw(Ordering::Acquire);
+        if ptr.is_null() { None } else { Some(unsafe { &*ptr }) }
+    }
+
+    /// Load the raw pointer without epoch protection.
+    pub fn load_raw(&self, ord: Ordering) -> *mut T {
+        self.ptr.load(ord) as *mut T
+    }
+
+    /// Store a raw pointer without epoch protection.
+    pub fn store_raw(&self, new: *mut T, ord: Ordering) {
+        self.ptr.store(new as usize, ord);
+    }
+
+    /// Compare-and-swap the raw pointer without epoch protection. Returns
+    /// `true` on success.
+    pub fn cas_raw(&self, old: *mut T, new: *mut T, ord: Ordering) -> bool {
+        self.ptr.compare_and_swap(old as usize, new as usize, ord) == old as usize
+    }
+}
+
+thread_local! {
+    static HANDLE: &'static Participant = global::get().participants.enroll();
+}
+
+/// Proof that the current thread is pinned: while one is alive, garbage
+/// retired by any thread can't be reclaimed out from under it. Not
+/// `Send`/`Sync` — a pin is inherently thread-local.
+pub struct Guard {
+    _marker: PhantomData<*mut ()>,
+}
+
+/// Pin the current thread, returning a guard valid until it's dropped.
+/// Enrolls the calling thread the first time it's called, and attempts to
+/// advance the global epoch once every `cadence()` pins (see
+/// `Participant::try_collect`).
+pub fn pin() -> Guard {
+    HANDLE.with(|p| p.enter());
+    let guard = Guard { _marker: PhantomData };
+    HANDLE.with(|p| p.try_collect(&guard));
+    guard
+}
+
+impl Guard {
+    fn participant(&self) -> &'static Participant {
+        HANDLE.with(|p| *p)
+    }
+
+    /// Defer an arbitrary finalizer until it's safe to run. See
+    /// `Participant::defer`.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.participant().defer(f);
+    }
+
+    /// Schedule a raw pointer to be freed once it's safe to do so. See
+    /// `Participant::reclaim`.
+    pub unsafe fn reclaim<T: 'static>(&self, data: *mut T) {
+        self.participant().reclaim(data);
+    }
+
+    /// Push this thread's local garbage into the global bags and
+    /// immediately attempt to advance the epoch. See `Participant::flush`.
+    pub fn flush(&self) {
+        let participant = self.participant();
+        participant.flush(self);
+    }
+
+    /// Like `flush`, but retries up to `attempts` times, re-pinning
+    /// between attempts. See `Participant::collect_now`.
+    pub fn collect_now(&mut self, attempts: usize) -> bool {
+        let participant = self.participant();
+        participant.collect_now(self, attempts)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        HANDLE.with(|p| p.exit());
+    }
+}
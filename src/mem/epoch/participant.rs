@@ -2,29 +2,69 @@
 // of the actual epoch management logic happens!
 
 use std::mem;
-use std::cell::UnsafeCell;
+use std::cell::{Cell, UnsafeCell};
 use std::sync::atomic::{self, AtomicUsize, AtomicBool};
 use std::sync::atomic::Ordering::{Relaxed, Acquire, Release, SeqCst};
 
 use mem::epoch::{Atomic, Guard, garbage, global};
 use mem::epoch::participants::ParticipantNode;
 
+/// Default number of pins between attempts to advance the global epoch.
+///
+/// Scanning every participant and CAS-ing the global epoch is the most
+/// expensive part of the reclamation scheme, so we only attempt it once
+/// every `CADENCE` pins rather than on every single one.
+pub const CADENCE: usize = 128;
+
+/// Read the current cadence between global-epoch advancement attempts.
+/// Tunable via `set_cadence` on the global handle so that data-structure
+/// authors can trade reclamation latency for throughput.
+pub fn cadence() -> usize {
+    global::get().cadence()
+}
+
+/// Set the cadence between global-epoch advancement attempts on the
+/// global handle. A smaller value advances the epoch more eagerly, trading
+/// throughput for lower reclamation latency; pass `1` to attempt an
+/// advance on every pin.
+pub fn set_cadence(cadence: usize) {
+    global::get().set_cadence(cadence);
+}
+
+/// `state` tag bit: set while the participant holds a live (possibly
+/// nested) critical section. Clear means "inactive" — the participant
+/// can't be blocking epoch advancement.
+const CRITICAL: usize = 1;
+
+/// `state` tag bit: set by `Participant::deactivate` to mark the participant
+/// terminated, only waiting to be unlinked from the list. A participant in this state
+/// can never block epoch advancement and is skipped by the scan.
+const INVALID: usize = 1 << 1;
+
+/// Number of low tag bits in `state`; the local epoch is packed above them.
+const TAG_BITS: usize = 2;
+
 /// Thread-local data for epoch participation.
+///
+/// The local epoch and the critical-section/active-ness of the participant
+/// are packed into a single `state` word (epoch in the high bits, `CRITICAL`
+/// and `INVALID` as low tag bits) so that `try_collect`'s scan over every
+/// participant costs one relaxed load each, instead of two.
 pub struct Participant {
-    /// The local epoch.
-    epoch: AtomicUsize,
+    /// Packed `(epoch << TAG_BITS) | tag bits`. See `CRITICAL` and `INVALID`.
+    state: AtomicUsize,
+
+    /// Nesting depth of `epoch::pin()` on this thread; only ever touched by
+    /// the owning thread, so it doesn't need to be part of `state`.
+    crit_count: Cell<usize>,
 
-    /// Number of pending uses of `epoch::pin()`; keeping a count allows for
-    /// reentrant use of epoch management.
-    in_critical: AtomicUsize,
+    /// Number of outermost pins seen so far, used to cadence attempts to
+    /// advance the global epoch in `try_collect`.
+    pin_count: AtomicUsize,
 
     /// Thread-local garbage tracking
     garbage: UnsafeCell<garbage::Local>,
 
-    /// Is the thread still active? Becomes `false` when the thread exits. This
-    /// is ultimately used to free `Participant` records.
-    pub active: AtomicBool,
-
     /// Has the thread been passed to unlinked() yet?
     /// Used to avoid a double free when reclaiming participants.
     pub unlinked: AtomicBool,
@@ -38,53 +78,90 @@ unsafe impl Sync for Participant {}
 impl Participant {
     pub fn new() -> Participant {
         Participant {
-            epoch: AtomicUsize::new(0),
-            in_critical: AtomicUsize::new(0),
-            active: AtomicBool::new(true),
+            state: AtomicUsize::new(0),
+            crit_count: Cell::new(0),
+            pin_count: AtomicUsize::new(0),
             unlinked: AtomicBool::new(false),
             garbage: UnsafeCell::new(garbage::Local::new()),
             next: Atomic::null(),
         }
     }
 
+    /// Is the thread still active? Becomes `false` once `deactivate` has
+    /// been called; exiting the thread does not call it.
+    /// `Participants::collect_participant` uses this to find terminated
+    /// participants; `Participant` records themselves are never freed.
+    pub fn active(&self) -> bool {
+        self.state.load(Relaxed) & INVALID == 0
+    }
+
+    /// Mark the participant as terminated; it can no longer block epoch
+    /// advancement, and is now only waiting to be unlinked from the list.
+    pub fn deactivate(&self) {
+        let cur = self.state.load(Relaxed);
+        self.state.store(cur | INVALID, Relaxed);
+    }
+
     /// Enter a critical section.
     ///
     /// This method is reentrant, allowing for nested critical sections.
     pub fn enter(&self) {
-        let new_count = self.in_critical.load(Relaxed) + 1;
-        self.in_critical.store(new_count, Relaxed);
+        let new_count = self.crit_count.get() + 1;
+        self.crit_count.set(new_count);
         if new_count > 1 { return }
 
+        self.pin_count.store(self.pin_count.load(Relaxed).wrapping_add(1), Relaxed);
+
         atomic::fence(SeqCst);
 
         let global_epoch = global::get().epoch.load(Relaxed);
-        if global_epoch != self.epoch.load(Relaxed) {
-            self.epoch.store(global_epoch, Relaxed);
+        let cur = self.state.load(Relaxed);
+        let local_epoch = cur >> TAG_BITS;
+        if global_epoch != local_epoch {
+            self.state.store((global_epoch << TAG_BITS) | CRITICAL | (cur & INVALID), Relaxed);
             unsafe { (*self.garbage.get()).collect(); }
+        } else {
+            self.state.store(cur | CRITICAL, Relaxed);
         }
     }
 
     /// Exit the current (nested) critical section.
     pub fn exit(&self) {
-        let new_count = self.in_critical.load(Relaxed) - 1;
-        self.in_critical.store(
-            new_count,
-            if new_count > 0 { Relaxed } else { Release });
+        let new_count = self.crit_count.get() - 1;
+        self.crit_count.set(new_count);
+        if new_count > 0 { return }
+
+        let cur = self.state.load(Relaxed);
+        self.state.store(cur & !CRITICAL, Release);
     }
 
     /// Begin the reclamation process for a piece of data.
-    pub unsafe fn reclaim<T>(&self, data: *mut T) {
+    pub unsafe fn reclaim<T: 'static>(&self, data: *mut T) {
         (*self.garbage.get()).reclaim(data);
     }
 
-    /// Attempt to collect garbage by moving the global epoch forward.
+    /// Defer an arbitrary finalizer until it's safe to run, i.e. until
+    /// every participant has observed two global-epoch advances since this
+    /// call. Unlike `reclaim`, which only frees a raw pointer, this can run
+    /// any destructor, drop a composite structure, or release a foreign
+    /// resource.
     ///
-    /// Returns `true` on success.
-    pub fn try_collect(&self, guard: &Guard) -> bool {
+    /// `f` may end up running on a different thread than the one that
+    /// deferred it — see `garbage::Local::defer` — so it must be `Send`.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F) {
+        unsafe { (*self.garbage.get()).defer(f); }
+    }
+
+    /// Scan every participant and, if none of them are blocking it, advance
+    /// the global epoch by one and collect this participant's share of the
+    /// now-freed garbage. Returns `true` on success.
+    fn advance_epoch(&self, guard: &Guard) -> bool {
         let cur_epoch = global::get().epoch.load(SeqCst);
 
         for p in global::get().participants.iter(guard) {
-            if p.in_critical.load(Relaxed) > 0 && p.epoch.load(Relaxed) != cur_epoch {
+            let state = p.state.load(Relaxed);
+            if state & INVALID != 0 { continue }
+            if state & CRITICAL != 0 && (state >> TAG_BITS) != cur_epoch {
                 return false
             }
         }
@@ -95,7 +172,8 @@ impl Participant {
             return false
         }
 
-        self.epoch.store(new_epoch, Relaxed);
+        let cur = self.state.load(Relaxed);
+        self.state.store((new_epoch << TAG_BITS) | (cur & ((1 << TAG_BITS) - 1)), Relaxed);
 
         unsafe {
             (*self.garbage.get()).collect();
@@ -105,17 +183,172 @@ impl Participant {
         true
     }
 
+    /// Attempt to collect garbage by moving the global epoch forward.
+    ///
+    /// To amortize the cost of scanning every participant and CAS-ing the
+    /// global epoch, this only actually attempts the advance when this
+    /// participant's pin count is a multiple of `cadence()`, i.e. once every
+    /// `cadence()` pins; the rest of the time it's a no-op. A thread still
+    /// picks up newly-collectible local garbage every time it observes a
+    /// new global epoch in `enter`, so this doesn't delay a thread's own
+    /// reclamation, only how often *this thread* tries to push the epoch
+    /// forward on everyone's behalf.
+    ///
+    /// Returns `true` on success.
+    pub fn try_collect(&self, guard: &Guard) -> bool {
+        if self.pin_count.load(Relaxed) % cadence() != 0 {
+            return false
+        }
+
+        self.advance_epoch(guard)
+    }
+
     /// Move the current thread-local garbage into the global garbage bags.
     pub fn migrate_garbage(&self) {
-        let cur_epoch = self.epoch.load(Relaxed);
+        let cur_epoch = self.state.load(Relaxed) >> TAG_BITS;
         let local = unsafe { mem::replace(&mut *self.garbage.get(), garbage::Local::new()) };
-        global::get().garbage[cur_epoch.wrapping_sub(1) % 3].insert(local.old);
+        // `retry` is at least as overdue as `old` (it's what a previous
+        // `collect()` couldn't finish finalizing before it panicked), so
+        // it goes in the same bucket, ahead of `old`.
+        let mut overdue = local.retry;
+        overdue.extend(local.old);
+        global::get().garbage[cur_epoch.wrapping_sub(1) % 3].insert(overdue);
         global::get().garbage[cur_epoch % 3].insert(local.cur);
         global::get().garbage[global::get().epoch.load(Relaxed) % 3].insert(local.new);
     }
 
+    /// Push this thread's local garbage into the global bags and
+    /// immediately attempt to advance the epoch, rather than waiting for
+    /// that to happen incidentally on some future pin. Bypasses the usual
+    /// cadence gating in `try_collect`, since a caller reaching for this
+    /// explicitly wants the garbage handed off right now — e.g. after
+    /// retiring a large batch of nodes (draining a queue), to keep
+    /// `garbage_size()` bounded instead of growing until the next pin
+    /// happens to land on a cadence boundary.
+    pub fn flush(&self, guard: &Guard) {
+        self.migrate_garbage();
+        self.advance_epoch(guard);
+    }
+
+    /// Like `flush`, but retries the epoch advance up to `attempts` times,
+    /// re-pinning between attempts, stopping as soon as one succeeds.
+    /// Returns `true` if the epoch was advanced.
+    ///
+    /// Re-pinning may observe a new global epoch and collect this thread's
+    /// own garbage right then and there, which would be unsound to do
+    /// while the caller can still be holding other borrows derived from
+    /// the guard's lifetime. Taking `&mut Guard` (mirroring upstream
+    /// crossbeam's `Guard::repin`) makes the borrow checker rule that out
+    /// statically, instead of requiring the caller to just not do that.
+    pub fn collect_now(&self, guard: &mut Guard, attempts: usize) -> bool {
+        self.migrate_garbage();
+        for _ in 0..attempts {
+            if self.advance_epoch(guard) {
+                return true
+            }
+            self.exit();
+            self.enter();
+        }
+        false
+    }
+
     /// How much garbage is this participant currently storing?
     pub fn garbage_size(&self) -> usize {
         unsafe { (*self.garbage.get()).size() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cadence_round_trips_through_the_global_handle_and_clamps_to_one() {
+        let prev = cadence();
+
+        set_cadence(7);
+        assert_eq!(cadence(), 7);
+        assert_eq!(global::get().cadence(), 7);
+
+        set_cadence(0);
+        assert_eq!(cadence(), 1, "set_cadence(0) should clamp up to 1, not disable gating");
+
+        set_cadence(prev);
+    }
+
+    #[test]
+    fn pin_count_increments_once_per_outermost_enter() {
+        let p = Participant::new();
+        assert_eq!(p.pin_count.load(Relaxed), 0);
+
+        p.enter();
+        p.enter(); // nested pin, shouldn't bump pin_count again
+        p.exit();
+        p.exit();
+        assert_eq!(p.pin_count.load(Relaxed), 1);
+
+        p.enter();
+        p.exit();
+        assert_eq!(p.pin_count.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn flush_always_migrates_local_garbage_into_the_global_bags() {
+        use std::sync::Arc;
+        use std::sync::atomic::Ordering;
+        use mem::epoch::pin;
+
+        let guard = pin();
+        let p = Participant::new();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let r = ran.clone();
+        p.defer(move || { r.fetch_add(1, Ordering::SeqCst); });
+        assert_eq!(p.garbage_size(), 1);
+
+        // flush() always hands local garbage off to the global bags, even
+        // if contention from other pinned threads keeps the epoch advance
+        // itself from succeeding right away.
+        p.flush(&guard);
+        assert_eq!(p.garbage_size(), 0);
+    }
+
+    #[test]
+    fn collect_now_terminates_within_the_given_attempts() {
+        use mem::epoch::pin;
+
+        let mut guard = pin();
+        let p = Participant::new();
+        let _ = p.collect_now(&mut guard, 3);
+    }
+
+    #[test]
+    fn deactivate_marks_participant_inactive() {
+        let p = Participant::new();
+        assert!(p.active());
+        p.deactivate();
+        assert!(!p.active());
+    }
+
+    #[test]
+    fn deactivate_invalid_bit_survives_enter_epoch_change() {
+        let p = Participant::new();
+
+        // Force enter()'s epoch-changed branch deterministically, without
+        // touching the real global epoch (shared with other tests), by
+        // making the participant's own recorded epoch stale.
+        let global_epoch = global::get().epoch.load(Relaxed);
+        let stale_epoch = global_epoch.wrapping_sub(1);
+        p.state.store((stale_epoch << TAG_BITS) | INVALID, Relaxed);
+
+        p.enter();
+
+        let state = p.state.load(Relaxed);
+        assert_eq!(state >> TAG_BITS, global_epoch,
+                   "enter() should adopt the current global epoch");
+        assert!(state & CRITICAL != 0, "enter() should mark the participant critical");
+        assert!(state & INVALID != 0,
+                "deactivate()'s INVALID tag must survive enter()'s epoch-change branch");
+
+        p.exit();
+    }
+}
@@ -0,0 +1,92 @@
+// The intrusively-linked list of registered participants.
+
+use std::ops::Deref;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+use mem::epoch::{Atomic, Guard};
+use mem::epoch::participant::Participant;
+
+/// A node in the participant list: a `Participant` plus the intrusive
+/// `next` pointer it already carries.
+pub struct ParticipantNode(pub Participant);
+
+impl Deref for ParticipantNode {
+    type Target = Participant;
+    fn deref(&self) -> &Participant { &self.0 }
+}
+
+/// The head of the registered-participants list.
+pub struct Participants {
+    head: Atomic<ParticipantNode>,
+}
+
+impl Participants {
+    pub fn new() -> Participants {
+        Participants { head: Atomic::null() }
+    }
+
+    /// Register a new participant, linking it onto the front of the list,
+    /// and return a reference to it.
+    ///
+    /// Participants are never physically unlinked, freed or reused (only
+    /// marked `INVALID` and flagged by `collect_participant`), so the
+    /// returned reference stays valid for the lifetime of the process.
+    /// Enrollment happens before the calling thread is pinned, so this
+    /// uses the unprotected raw accessors rather than `load`/a `Guard`:
+    /// the node being pushed isn't reachable from anywhere else yet, and
+    /// reading the current head only to compare it in a CAS doesn't
+    /// require protecting it from concurrent reclamation.
+    pub fn enroll(&self) -> &'static Participant {
+        let new = Box::into_raw(Box::new(ParticipantNode(Participant::new())));
+        let node: &ParticipantNode = unsafe { &*new };
+        loop {
+            let head = self.head.load_raw(Acquire);
+            node.next.store_raw(head, Relaxed);
+            if self.head.cas_raw(head, new, Release) {
+                return &node.0;
+            }
+        }
+    }
+
+    /// Iterate over every registered participant, active or terminated.
+    pub fn iter<'a>(&'a self, guard: &'a Guard) -> Iter<'a> {
+        Iter { next: &self.head, guard: guard }
+    }
+
+    /// Find a terminated participant that hasn't yet been unlinked and
+    /// mark it as such, returning `true` if one was found. The node itself
+    /// stays in the list and is not freed.
+    ///
+    /// A participant's liveness now lives entirely in its packed `state`
+    /// word (see `Participant::active`), so this checks `active()` rather
+    /// than a separate `active` field, and can no longer observe it
+    /// changing mid-check the way two separate loads could.
+    pub fn collect_participant(&self, guard: &Guard) -> bool {
+        for p in self.iter(guard) {
+            if !p.active() && !p.unlinked.swap(true, Relaxed) {
+                return true
+            }
+        }
+        false
+    }
+}
+
+/// An iterator over every registered `Participant`.
+pub struct Iter<'a> {
+    next: &'a Atomic<ParticipantNode>,
+    guard: &'a Guard,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Participant;
+
+    fn next(&mut self) -> Option<&'a Participant> {
+        match self.next.load(self.guard) {
+            Some(node) => {
+                self.next = &node.next;
+                Some(&*node)
+            }
+            None => None,
+        }
+    }
+}